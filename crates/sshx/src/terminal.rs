@@ -2,48 +2,125 @@
 
 #![allow(unsafe_code)]
 
+use std::collections::HashMap;
 use std::convert::Infallible;
 use std::env;
-use std::ffi::{CStr, CString};
-use std::os::fd::{AsRawFd, RawFd};
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::os::fd::{AsRawFd, BorrowedFd, RawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use close_fds::CloseFdsBuilder;
 use nix::errno::Errno;
 use nix::libc::{login_tty, TIOCGWINSZ, TIOCSWINSZ};
 use nix::pty::{self, Winsize};
-use nix::sys::signal::{kill, Signal::SIGKILL};
-use nix::unistd::{execvp, fork, ForkResult, Pid};
+use nix::sys::signal::{kill, Signal};
+use nix::sys::termios::{self, LocalFlags, SetArg, Termios};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{chdir, execvpe, fork, tcgetpgrp, ForkResult, Pid};
 use pin_project::{pin_project, pinned_drop};
 use tokio::fs::File;
 use tokio::io::{self, AsyncRead, AsyncWrite};
 use tracing::{instrument, trace};
 
+/// How long to wait after sending `SIGHUP` before escalating to `SIGKILL`.
+const SHUTDOWN_GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+/// How the child process exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// The process called `exit()` with this status code.
+    Exited(i32),
+    /// The process was terminated by this signal.
+    Signaled(Signal),
+}
+
 /// Returns the default shell on this system.
 pub fn get_default_shell() -> String {
     env::var("SHELL").unwrap_or_else(|_| String::from("/bin/bash"))
 }
 
-/// An object that stores the state for a terminal session.
-#[pin_project(PinnedDrop)]
-pub struct Terminal {
-    child: Pid,
-    #[pin]
-    master_read: File,
-    #[pin]
-    master_write: File,
+/// A builder for configuring and spawning a new [`Terminal`].
+///
+/// Borrows the design of pty-process's `Command`: callers can override the
+/// argv passed to `execvpe`, layer environment variables on top of the
+/// terminal's defaults, and set a working directory for the spawned shell.
+pub struct TerminalBuilder {
+    argv: Vec<String>,
+    envs: Vec<(String, Option<String>)>,
+    dir: Option<PathBuf>,
 }
 
-impl Terminal {
-    /// Create a new terminal, with attached PTY.
-    #[instrument]
-    pub async fn new(shell: &str) -> Result<Terminal> {
+impl TerminalBuilder {
+    /// Start building a terminal that runs the given shell with no arguments.
+    pub fn new(shell: impl Into<String>) -> Self {
+        Self {
+            argv: vec![shell.into()],
+            envs: Vec::new(),
+            dir: None,
+        }
+    }
+
+    /// Append an argument to the spawned shell's argv.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.argv.push(arg.into());
+        self
+    }
+
+    /// Append multiple arguments to the spawned shell's argv.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.argv.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set an environment variable in the child, overriding the terminal's
+    /// built-in `TERM`/`COLORTERM` defaults if they collide.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.envs.push((key.into(), Some(value.into())));
+        self
+    }
+
+    /// Remove an environment variable from the child's environment.
+    pub fn env_remove(mut self, key: impl Into<String>) -> Self {
+        self.envs.push((key.into(), None));
+        self
+    }
+
+    /// Set the working directory of the spawned shell.
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Spawn the configured shell, attached to a new PTY.
+    #[instrument(skip_all)]
+    pub async fn spawn(self) -> Result<Terminal> {
         let result = pty::openpty(None, None)?;
 
-        // The slave file descriptor was created by openpty() and is forked here.
-        let child = Self::fork_child(shell, result.slave.as_raw_fd())?;
+        // Precompute every `CString` needed by the child in the parent process, since
+        // the child branch after `fork()` must remain async-signal-safe and cannot
+        // allocate.
+        let argv = self
+            .argv
+            .iter()
+            .map(|arg| CString::new(arg.as_str()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let envp = build_envp(&self.envs)?;
+        let dir = self
+            .dir
+            .as_deref()
+            .map(path_to_cstring)
+            .transpose()?;
+
+        let child = Terminal::fork_child(&argv, &envp, dir.as_deref(), result.slave.as_raw_fd())?;
 
         // We need to clone the file object to prevent livelocks in Tokio, when multiple
         // reads and writes happen concurrently on the same file descriptor. This is a
@@ -54,43 +131,76 @@ impl Terminal {
 
         trace!(%child, "creating new terminal");
 
-        Ok(Self {
+        Ok(Terminal {
             child,
+            reaped: false,
             master_read,
             master_write,
         })
     }
+}
 
-    /// Entry point for the child process, which spawns a shell.
-    fn fork_child(shell: &str, slave_port: RawFd) -> Result<Pid> {
-        let shell = CString::new(shell.to_owned())?;
+/// An object that stores the state for a terminal session.
+///
+/// Prefer calling [`Terminal::close`] (or [`Terminal::wait`]) before dropping this,
+/// rather than relying on the `Drop` fallback: those reap the child off-thread and
+/// report its exit status, while the fallback only has a short, synchronous budget
+/// to reap it.
+#[pin_project(PinnedDrop)]
+pub struct Terminal {
+    child: Pid,
+    /// Whether the child has already been reaped by [`Terminal::wait`] or
+    /// [`Terminal::close`].
+    reaped: bool,
+    #[pin]
+    master_read: File,
+    #[pin]
+    master_write: File,
+}
 
+impl Terminal {
+    /// Create a new terminal, with attached PTY, running the given shell.
+    #[instrument]
+    pub async fn new(shell: &str) -> Result<Terminal> {
+        TerminalBuilder::new(shell).spawn().await
+    }
+
+    /// Entry point for the child process, which spawns a shell.
+    fn fork_child(
+        argv: &[CString],
+        envp: &[CString],
+        dir: Option<&CStr>,
+        slave_port: RawFd,
+    ) -> Result<Pid> {
         // Safety: This does not use any async-signal-unsafe operations in the child
         // branch, such as memory allocation.
         match unsafe { fork() }? {
             ForkResult::Parent { child } => Ok(child),
-            ForkResult::Child => match Self::execv_child(&shell, slave_port) {
+            ForkResult::Child => match Self::execv_child(argv, envp, dir, slave_port) {
                 Ok(infallible) => match infallible {},
                 Err(_) => std::process::exit(1),
             },
         }
     }
 
-    fn execv_child(shell: &CStr, slave_port: RawFd) -> Result<Infallible, Errno> {
+    fn execv_child(
+        argv: &[CString],
+        envp: &[CString],
+        dir: Option<&CStr>,
+        slave_port: RawFd,
+    ) -> Result<Infallible, Errno> {
         // Safety: The slave file descriptor was created by openpty().
         Errno::result(unsafe { login_tty(slave_port) })?;
         // Safety: This is called immediately before an execv(), and there are no other
         // threads in this process to interact with its file descriptor table.
         unsafe { CloseFdsBuilder::new().closefrom(3) };
 
-        // Set terminal environment variables appropriately.
-        env::set_var("TERM", "xterm-256color");
-        env::set_var("COLORTERM", "truecolor");
-        env::set_var("TERM_PROGRAM", "sshx");
-        env::remove_var("TERM_PROGRAM_VERSION");
+        if let Some(dir) = dir {
+            chdir(dir)?;
+        }
 
         // Start the process.
-        execvp(shell, &[shell])
+        execvpe(&argv[0], argv, envp)
     }
 
     /// Get the window size of the TTY.
@@ -110,6 +220,126 @@ impl Terminal {
         unsafe { ioctl_set_winsize(self.master_read.as_raw_fd(), &winsize) }?;
         Ok(())
     }
+
+    /// Get the terminal attributes (termios) of the master fd.
+    pub fn get_attr(&self) -> Result<Termios> {
+        Ok(termios::tcgetattr(self.master_fd())?)
+    }
+
+    /// Set the terminal attributes (termios) of the master fd, taking effect immediately.
+    pub fn set_attr(&self, attr: &Termios) -> Result<()> {
+        termios::tcsetattr(self.master_fd(), SetArg::TCSANOW, attr)?;
+        Ok(())
+    }
+
+    /// Put the terminal into raw mode, disabling canonical processing, echo, and signal
+    /// generation so that the bytes passed through are not reinterpreted.
+    pub fn set_raw(&self) -> Result<()> {
+        let mut attr = self.get_attr()?;
+        termios::cfmakeraw(&mut attr);
+        self.set_attr(&attr)
+    }
+
+    /// Enable or disable echoing of input characters back to the terminal.
+    pub fn set_echo(&self, echo: bool) -> Result<()> {
+        let mut attr = self.get_attr()?;
+        attr.local_flags.set(LocalFlags::ECHO, echo);
+        self.set_attr(&attr)
+    }
+
+    /// Send a signal to the foreground process group running in the terminal.
+    ///
+    /// This resolves the foreground process group via `tcgetpgrp()`, so it reaches
+    /// whatever job currently owns the terminal (not just the session-leader shell),
+    /// matching what a real terminal does when it delivers Ctrl-C or Ctrl-\.
+    pub fn send_signal(&self, signal: Signal) -> Result<()> {
+        let pgid = tcgetpgrp(self.master_fd())?;
+        kill(Pid::from_raw(-pgid.as_raw()), signal)?;
+        Ok(())
+    }
+
+    /// Forcibly kill the foreground process group running in the terminal.
+    pub fn kill_group(&self) -> Result<()> {
+        self.send_signal(Signal::SIGKILL)
+    }
+
+    /// Borrow the master fd for use with APIs that take `BorrowedFd`, such as `termios`.
+    fn master_fd(&self) -> BorrowedFd<'_> {
+        // Safety: The master file descriptor was created by openpty() and outlives this
+        // borrow, which does not escape the call that uses it.
+        unsafe { BorrowedFd::borrow_raw(self.master_read.as_raw_fd()) }
+    }
+
+    /// Spawn the blocking `waitpid(..., WNOHANG)` poll loop for the child.
+    ///
+    /// Returns a single join handle that resolves exactly once the child is reaped,
+    /// so that callers can race it against a timeout without starting a second
+    /// concurrent `waitpid` on the same PID (which would otherwise race for the
+    /// exit status and leave the loser with a spurious `ECHILD`).
+    fn spawn_wait(&self) -> Result<tokio::task::JoinHandle<Result<WaitStatus>>> {
+        if self.reaped {
+            return Err(anyhow!("child process {} was already reaped", self.child));
+        }
+
+        let child = self.child;
+        Ok(tokio::task::spawn_blocking(move || -> Result<WaitStatus> {
+            loop {
+                match waitpid(child, Some(WaitPidFlag::WNOHANG))? {
+                    WaitStatus::StillAlive => std::thread::sleep(Duration::from_millis(50)),
+                    status => return Ok(status),
+                }
+            }
+        }))
+    }
+
+    fn to_exit_status(status: WaitStatus) -> ExitStatus {
+        match status {
+            WaitStatus::Exited(_, code) => ExitStatus::Exited(code),
+            WaitStatus::Signaled(_, signal, _) => ExitStatus::Signaled(signal),
+            // We don't pass `WUNTRACED`/`WCONTINUED` above, so `waitpid(WNOHANG)` can only
+            // ever report `Exited`, `Signaled`, or `StillAlive` here; a stopped child (e.g.
+            // via Ctrl-Z) is intentionally treated as still running rather than reaped.
+            status => unreachable!(
+                "waitpid(WNOHANG) without WUNTRACED/WCONTINUED cannot return {status:?}"
+            ),
+        }
+    }
+
+    /// Wait for the child process to exit, reaping it and returning its exit status.
+    ///
+    /// This polls `waitpid(..., WNOHANG)` from a blocking thread so that it doesn't
+    /// block the async runtime while the child is still alive.
+    pub async fn wait(&mut self) -> Result<ExitStatus> {
+        let status = self.spawn_wait()?.await??;
+        self.reaped = true;
+        Ok(Self::to_exit_status(status))
+    }
+
+    /// Gracefully shut down the child process and return its exit status.
+    ///
+    /// Sends `SIGHUP` to the child's process group so the shell has a chance to run
+    /// its own cleanup logic, then escalates to `SIGKILL` if it is still alive after
+    /// [`SHUTDOWN_GRACE_PERIOD`]. Only one `waitpid` loop is ever in flight: the same
+    /// join handle is awaited across the grace period and the post-`SIGKILL` wait, so
+    /// the two phases can't race each other for the child's exit status.
+    pub async fn close(&mut self) -> Result<ExitStatus> {
+        // Safety: login_tty() made the child a session and process group leader, so
+        // its process group ID is equal to its PID.
+        kill(Pid::from_raw(-self.child.as_raw()), Signal::SIGHUP).ok();
+
+        let handle = self.spawn_wait()?;
+        tokio::pin!(handle);
+
+        let status = tokio::select! {
+            result = &mut handle => result??,
+            _ = tokio::time::sleep(SHUTDOWN_GRACE_PERIOD) => {
+                kill(Pid::from_raw(-self.child.as_raw()), Signal::SIGKILL).ok();
+                (&mut handle).await??
+            }
+        };
+        self.reaped = true;
+        Ok(Self::to_exit_status(status))
+    }
 }
 
 // Redirect terminal reads to the read file object.
@@ -142,14 +372,36 @@ impl AsyncWrite for Terminal {
     }
 }
 
+/// Retry budget for the fallback reap in [`PinnedDrop::drop`].
+///
+/// `drop` runs synchronously, possibly on a Tokio worker thread, so it must not stall
+/// the executor for long. Prefer calling [`Terminal::close`] or [`Terminal::wait`]
+/// before dropping a `Terminal`: both reap the child off-thread via `spawn_blocking`
+/// and report its exit status, whereas this is a last-resort cleanup for terminals
+/// that were simply dropped.
+const DROP_REAP_RETRIES: u32 = 5;
+
 #[pinned_drop]
 impl PinnedDrop for Terminal {
     fn drop(self: Pin<&mut Self>) {
         let this = self.project();
         trace!(child = %this.child, "dropping terminal");
 
-        // Reap the child process on closure so that it doesn't keep running.
-        kill(*this.child, SIGKILL).ok();
+        // If the terminal wasn't gracefully shut down with `close()`, fall back to an
+        // unconditional kill here. Guard against reaping a PID that was already
+        // collected by `wait()`. The kernel needs a moment to deliver the signal and
+        // transition the child to a waitable state, so retry the reap a few times
+        // instead of giving up after a single non-blocking `waitpid` call, while
+        // keeping the total spin short enough not to stall the caller's thread.
+        if !*this.reaped {
+            kill(Pid::from_raw(-this.child.as_raw()), Signal::SIGKILL).ok();
+            for _ in 0..DROP_REAP_RETRIES {
+                match waitpid(*this.child, Some(WaitPidFlag::WNOHANG)) {
+                    Ok(WaitStatus::StillAlive) => std::thread::sleep(Duration::from_millis(5)),
+                    _ => break,
+                }
+            }
+        }
     }
 }
 
@@ -162,11 +414,59 @@ fn make_winsize(rows: u16, cols: u16) -> Winsize {
     }
 }
 
+fn path_to_cstring(path: &Path) -> Result<CString> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+/// Build the `KEY=VALUE` environment entries for the child, layering the terminal's
+/// default `TERM`/`COLORTERM` variables and the caller's overrides on top of the
+/// current process's environment.
+///
+/// Uses `vars_os`/`OsStr` throughout rather than `vars`/`str`, since the latter panics
+/// if any inherited environment entry isn't valid UTF-8. Inherited entries are passed
+/// to the child byte for byte without being decoded, so a non-UTF-8 entry (e.g. from a
+/// locale or `PATH` seeded by another tool) does not turn spawning a shell into a
+/// panic.
+fn build_envp(overrides: &[(String, Option<String>)]) -> Result<Vec<CString>> {
+    let mut vars: HashMap<OsString, OsString> = env::vars_os().collect();
+
+    // Set terminal environment variables appropriately.
+    vars.insert("TERM".into(), "xterm-256color".into());
+    vars.insert("COLORTERM".into(), "truecolor".into());
+    vars.insert("TERM_PROGRAM".into(), "sshx".into());
+    vars.remove(OsStr::new("TERM_PROGRAM_VERSION"));
+
+    for (key, value) in overrides {
+        match value {
+            Some(value) => {
+                vars.insert(OsString::from(key), OsString::from(value));
+            }
+            None => {
+                vars.remove(OsStr::new(key));
+            }
+        }
+    }
+
+    vars.into_iter()
+        .map(|(key, value)| {
+            let mut entry = key.into_vec();
+            entry.push(b'=');
+            entry.extend(value.into_vec());
+            Ok(CString::new(entry)?)
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use anyhow::Result;
+    use nix::sys::signal::Signal;
+    use nix::sys::termios::LocalFlags;
+    use tokio::io::AsyncReadExt;
 
-    use super::Terminal;
+    use super::{ExitStatus, Terminal, TerminalBuilder};
 
     #[tokio::test]
     async fn winsize() -> Result<()> {
@@ -176,4 +476,153 @@ mod tests {
         assert_eq!(terminal.get_winsize()?, (120, 72));
         Ok(())
     }
+
+    #[tokio::test]
+    async fn wait_reports_exit_status() -> Result<()> {
+        let mut terminal = TerminalBuilder::new("/bin/sh")
+            .arg("-c")
+            .arg("exit 42")
+            .spawn()
+            .await?;
+        assert_eq!(terminal.wait().await?, ExitStatus::Exited(42));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn close_reports_exit_status() -> Result<()> {
+        let mut terminal = TerminalBuilder::new("/bin/sh")
+            .arg("-c")
+            .arg("exit 7")
+            .spawn()
+            .await?;
+
+        // Let the shell exit on its own first: `close()` starts by sending `SIGHUP`,
+        // which would otherwise end a still-running shell as `Signaled(SIGHUP)`.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(terminal.close().await?, ExitStatus::Exited(7));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn env_override_reaches_child() -> Result<()> {
+        let mut terminal = TerminalBuilder::new("/bin/sh")
+            .arg("-c")
+            .arg("printf '%s' \"$SSHX_TEST_VAR\"")
+            .env("SSHX_TEST_VAR", "hello")
+            .spawn()
+            .await?;
+
+        // Read exactly the bytes we expect instead of looping to EOF: once the child
+        // exits and the slave closes, reading the PTY master returns `EIO`, not `Ok(0)`.
+        let mut output = [0u8; 5];
+        terminal.read_exact(&mut output).await?;
+        assert_eq!(&output, b"hello");
+
+        terminal.wait().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn env_remove_removes_env_override() -> Result<()> {
+        let mut terminal = TerminalBuilder::new("/bin/sh")
+            .arg("-c")
+            .arg("printf '[%s]' \"$SSHX_TEST_REMOVED\"")
+            .env("SSHX_TEST_REMOVED", "visible")
+            .env_remove("SSHX_TEST_REMOVED")
+            .spawn()
+            .await?;
+
+        let mut output = [0u8; 2];
+        terminal.read_exact(&mut output).await?;
+        assert_eq!(&output, b"[]");
+
+        terminal.wait().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn args_appends_multiple_arguments() -> Result<()> {
+        let mut terminal = TerminalBuilder::new("/bin/echo")
+            .args(["foo", "bar"])
+            .spawn()
+            .await?;
+
+        let mut output = [0u8; 16];
+        let n = terminal.read(&mut output).await?;
+        assert_eq!(String::from_utf8_lossy(&output[..n]).trim(), "foo bar");
+
+        terminal.wait().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dir_changes_child_working_directory() -> Result<()> {
+        let dir = std::env::temp_dir().canonicalize()?;
+
+        let mut terminal = TerminalBuilder::new("/bin/sh")
+            .arg("-c")
+            .arg("pwd")
+            .dir(&dir)
+            .spawn()
+            .await?;
+
+        let mut output = [0u8; 256];
+        let n = terminal.read(&mut output).await?;
+        let printed = String::from_utf8_lossy(&output[..n]);
+        assert_eq!(printed.trim(), dir.to_str().unwrap());
+
+        terminal.wait().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn termios_set_echo_and_set_raw() -> Result<()> {
+        let terminal = Terminal::new("/bin/sh").await?;
+
+        assert!(terminal.get_attr()?.local_flags.contains(LocalFlags::ECHO));
+        terminal.set_echo(false)?;
+        assert!(!terminal.get_attr()?.local_flags.contains(LocalFlags::ECHO));
+
+        terminal.set_raw()?;
+        assert!(!terminal.get_attr()?.local_flags.contains(LocalFlags::ICANON));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn send_signal_terminates_foreground_job() -> Result<()> {
+        let mut terminal = TerminalBuilder::new("/bin/sh")
+            .arg("-c")
+            .arg("exec sleep 100")
+            .spawn()
+            .await?;
+
+        // Give the shell a moment to exec into the foreground job before signalling it.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        terminal.send_signal(Signal::SIGTERM)?;
+
+        assert_eq!(
+            terminal.wait().await?,
+            ExitStatus::Signaled(Signal::SIGTERM)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn kill_group_force_kills_foreground_job() -> Result<()> {
+        let mut terminal = TerminalBuilder::new("/bin/sh")
+            .arg("-c")
+            .arg("exec sleep 100")
+            .spawn()
+            .await?;
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        terminal.kill_group()?;
+
+        assert_eq!(
+            terminal.wait().await?,
+            ExitStatus::Signaled(Signal::SIGKILL)
+        );
+        Ok(())
+    }
 }